@@ -9,12 +9,14 @@ use std::collections::HashMap;
 use std::env;
 use std::ffi::OsString;
 use std::fs;
-use std::io::Read;
+use std::io::{BufRead, BufReader, Read};
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Instant, SystemTime};
 
-use crate::core::compiler::{CrateType, OutputFile, Unit};
-use crate::core::{Edition, Features, Target};
+use crate::core::compiler::{CrateType, FileFlavor, OutputFile, Unit};
+use crate::core::{Edition, Features, Target, TargetKind};
 use crate::util::toml::TomlManifest;
 use crate::util::{closest_msg, CargoResult};
 use cargo_util::ProcessBuilder;
@@ -32,31 +34,211 @@ fn is_executable<P: AsRef<Path>>(path: P) -> bool {
     path.as_ref().is_file()
 }
 
+/// Reply to the `fingerprint` subcommand, describing the compiler/driver
+/// version and any environment that affects the output it produces.
+#[derive(serde::Deserialize, serde::Serialize)]
+struct FingerprintReply {
+    version: String,
+    env: Vec<(String, String)>,
+    extra: u64,
+}
+
+/// Fixed 128-bit key for [`siphash13`].
+///
+/// The values are arbitrary, but must never change: a toolchain
+/// fingerprint is only useful if identical inputs hash to the same
+/// value across every `alen` run and every machine.
+const SIPHASH_KEY: (u64, u64) = (0x6a65_6865_6e64_7269, 0x7820_616c_656e_2121);
+
+/// A small, dependency-free SipHash-1-3 (one compression round per
+/// 8-byte block, three finalization rounds) with a fixed key.
+///
+/// `std::collections::hash_map::DefaultHasher` is deliberately not used
+/// here: its output is explicitly *not* guaranteed to be stable across
+/// Rust releases, which would make toolchain fingerprints built from it
+/// change out from under us without the toolchain itself changing.
+fn siphash13(data: &[u8]) -> u64 {
+    let (k0, k1) = SIPHASH_KEY;
+    siphash13_with_key(k0, k1, data)
+}
+
+/// SipHash-1-3 parameterized over its 128-bit key, split out from
+/// [`siphash13`] so the algorithm itself can be checked against known
+/// test vectors under the canonical SipHash test key, independent of
+/// alen's own fixed [`SIPHASH_KEY`].
+fn siphash13_with_key(k0: u64, k1: u64, data: &[u8]) -> u64 {
+    let mut v0 = k0 ^ 0x736f_6d65_7073_6575;
+    let mut v1 = k1 ^ 0x646f_7261_6e64_6f6d;
+    let mut v2 = k0 ^ 0x6c79_6765_6e65_7261;
+    let mut v3 = k1 ^ 0x7465_6462_7974_6573;
+
+    macro_rules! sipround {
+        () => {{
+            v0 = v0.wrapping_add(v1);
+            v1 = v1.rotate_left(13);
+            v1 ^= v0;
+            v0 = v0.rotate_left(32);
+            v2 = v2.wrapping_add(v3);
+            v3 = v3.rotate_left(16);
+            v3 ^= v2;
+            v0 = v0.wrapping_add(v3);
+            v3 = v3.rotate_left(21);
+            v3 ^= v0;
+            v2 = v2.wrapping_add(v1);
+            v1 = v1.rotate_left(17);
+            v1 ^= v2;
+            v2 = v2.rotate_left(32);
+        }};
+    }
+
+    let mut chunks = data.chunks_exact(8);
+    for chunk in &mut chunks {
+        let m = u64::from_le_bytes(chunk.try_into().unwrap());
+        v3 ^= m;
+        sipround!();
+        v0 ^= m;
+    }
+
+    let remainder = chunks.remainder();
+    let mut last_block = [0u8; 8];
+    last_block[..remainder.len()].copy_from_slice(remainder);
+    last_block[7] = data.len() as u8;
+    let m = u64::from_le_bytes(last_block);
+    v3 ^= m;
+    sipround!();
+    v0 ^= m;
+
+    v2 ^= 0xff;
+    sipround!();
+    sipround!();
+    sipround!();
+
+    v0 ^ v1 ^ v2 ^ v3
+}
+
+/// Hash a [`FingerprintReply`] into the stable `u64` stored as
+/// [`BuildSystem::hash`].
+fn hash_fingerprint_reply(reply: &FingerprintReply) -> u64 {
+    let mut buf = Vec::new();
+    buf.extend_from_slice(reply.version.as_bytes());
+    buf.push(0);
+    for (key, value) in &reply.env {
+        buf.extend_from_slice(key.as_bytes());
+        buf.push(0);
+        buf.extend_from_slice(value.as_bytes());
+        buf.push(0);
+    }
+    buf.extend_from_slice(&reply.extra.to_le_bytes());
+    siphash13(&buf)
+}
+
+/// Cache of toolchain hashes keyed by executable path and mtime, so the
+/// `fingerprint` subprocess isn't re-spawned on every build graph
+/// traversal.
+fn fingerprint_cache() -> &'static Mutex<HashMap<(PathBuf, SystemTime), u64>> {
+    static CACHE: OnceLock<Mutex<HashMap<(PathBuf, SystemTime), u64>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Spawn `path fingerprint` and parse its JSON reply.
+fn query_fingerprint(path: &Path) -> CargoResult<FingerprintReply> {
+    let mut command = Command::new(path.as_os_str());
+    command.arg("fingerprint");
+    command.env_clear();
+    command.stdin(Stdio::null());
+    command.stdout(Stdio::piped());
+    command.stderr(Stdio::null());
+    let mut child = command
+        .spawn()
+        .map_err(|_| anyhow::format_err!("Could not launch {}", path.display()))?;
+
+    let mut stdout = child.stdout.take().unwrap();
+    let mut buffer = String::new();
+    stdout.read_to_string(&mut buffer)?;
+    let ecode = child
+        .wait()
+        .map_err(|_| anyhow::format_err!("{} failed to terminate", path.display()))?;
+    check_exit_status(path, ecode)?;
+
+    serde_json::from_str(&buffer)
+        .with_context(|| format!("Invalid fingerprint reply from `{}`", path.display()))
+}
+
+/// Check a child's exit status, distinguishing a non-zero exit code
+/// from death by signal (`status.code()` is `None` when a process is
+/// killed by a signal rather than exiting normally).
+fn check_exit_status(path: &Path, status: std::process::ExitStatus) -> CargoResult<()> {
+    if status.success() {
+        return Ok(());
+    }
+    match status.code() {
+        Some(code) => bail!("{} exited with code {}", path.display(), code),
+        None => bail!("{} was terminated by a signal ({})", path.display(), status),
+    }
+}
+
+/// Compute (or fetch from cache) the toolchain hash for `path`, mixing
+/// in the compiler/driver version and environment the tool reports.
+fn compute_toolchain_hash(path: &Path) -> CargoResult<u64> {
+    let mtime = fs::metadata(path)
+        .with_context(|| format!("failed to stat {}", path.display()))?
+        .modified()?;
+    let key = (path.to_path_buf(), mtime);
+    if let Some(hash) = fingerprint_cache().lock().unwrap().get(&key) {
+        return Ok(*hash);
+    }
+
+    let reply = query_fingerprint(path)?;
+    let hash = hash_fingerprint_reply(&reply);
+    fingerprint_cache().lock().unwrap().insert(key, hash);
+    Ok(hash)
+}
+
 /// Provides information specific to building a package in a specific build system.
 #[derive(Debug)]
 struct BuildSystem {
     /// Path to executable to run.
     path: PathBuf,
-    /// Hash of the build system toolset.
-    hash: u64,
+    /// Hash of the build system toolset, mixed in from the tool's
+    /// `fingerprint` reply the same way Cargo mixes in rustc's version
+    /// when fingerprinting rustc units. Computed lazily, on first use
+    /// of this *specific* build system, rather than eagerly at
+    /// discovery time: an external tool is only spawned for packages
+    /// that actually request it, so a stale or hung `cargobuild-*`
+    /// binary the current build never uses can't break or block
+    /// discovery for every other build system.
+    hash: Mutex<Option<u64>>,
 }
 
 impl BuildSystem {
     pub fn new(path: PathBuf) -> Self {
         Self {
             path,
-            hash: 0, // TODO: FIXME
+            hash: Mutex::new(None),
         }
     }
 
-    pub fn hash(&self) -> u64 {
-        self.hash
+    pub fn hash(&self) -> CargoResult<u64> {
+        let mut cached = self.hash.lock().unwrap();
+        if let Some(hash) = *cached {
+            return Ok(hash);
+        }
+        let hash = compute_toolchain_hash(&self.path)?;
+        *cached = Some(hash);
+        Ok(hash)
     }
 
     /// Return new process builder for running build command.
     fn command(&self) -> ProcessBuilder {
         ProcessBuilder::new(self.path.as_os_str())
     }
+
+    /// Return a `std::process::Command` for the build command, for
+    /// callers that need to stream its stdout rather than hand it off
+    /// to `ProcessBuilder`.
+    fn command_std(&self) -> Command {
+        Command::new(self.path.as_os_str())
+    }
 }
 
 /// Encapsulates access to external build systems.
@@ -72,10 +254,38 @@ pub struct TargetRequest {
     pub package_root: OsString,
 }
 
+/// Wire format for [`Target::kind`], mirroring `TargetKind`'s variants
+/// so payloads sent to external tools are a documented, stable
+/// encoding instead of `{:?}` formatting. [`ExternalBuildMgr::targets`]
+/// only accepts [`ExtTargetKind::Bin`]/[`ExtTargetKind::Lib`] back from
+/// a tool, since an external build system only defines library/binary
+/// targets, and fails with an error on any other kind a tool reports;
+/// the other variants appear in
+/// [`UnitRequest`] for targets alen synthesizes itself (tests,
+/// benches, examples, build scripts).
 #[derive(serde::Deserialize, serde::Serialize)]
 pub enum ExtTargetKind {
     Bin,
     Lib,
+    Test,
+    Bench,
+    ExampleBin,
+    ExampleLib,
+    CustomBuild,
+}
+
+impl ExtTargetKind {
+    fn from_target_kind(kind: &TargetKind) -> Self {
+        match kind {
+            TargetKind::Bin => ExtTargetKind::Bin,
+            TargetKind::Lib(_) => ExtTargetKind::Lib,
+            TargetKind::Test => ExtTargetKind::Test,
+            TargetKind::Bench => ExtTargetKind::Bench,
+            TargetKind::ExampleBin => ExtTargetKind::ExampleBin,
+            TargetKind::ExampleLib(_) => ExtTargetKind::ExampleLib,
+            TargetKind::CustomBuild => ExtTargetKind::CustomBuild,
+        }
+    }
 }
 
 #[derive(serde::Deserialize, serde::Serialize)]
@@ -100,6 +310,15 @@ impl ExtTarget {
                 PathBuf::from(&self.src_path),
                 Edition::Edition2021,
             )),
+            ExtTargetKind::Test
+            | ExtTargetKind::Bench
+            | ExtTargetKind::ExampleBin
+            | ExtTargetKind::ExampleLib
+            | ExtTargetKind::CustomBuild => bail!(
+                "external build system reported target `{}` with kind that \
+                 only alen itself may synthesize",
+                self.name
+            ),
         }
     }
 }
@@ -116,6 +335,338 @@ pub enum TargetResult {
     },
 }
 
+/// Description of a [`Unit`] sent to the `outputs` subcommand so the
+/// external tool knows what artifact layout Cargo expects.
+#[derive(serde::Serialize)]
+struct UnitRequest {
+    target_name: String,
+    kind: ExtTargetKind,
+    profile: String,
+    cfgs: Vec<String>,
+    out_dir: OsString,
+}
+
+impl UnitRequest {
+    fn new(unit: &Unit, out_dir: &Path) -> Self {
+        UnitRequest {
+            target_name: unit.target.name().to_string(),
+            kind: ExtTargetKind::from_target_kind(unit.target.kind()),
+            profile: unit.profile.name.to_string(),
+            cfgs: unit.features.iter().map(|f| f.to_string()).collect(),
+            out_dir: out_dir.as_os_str().to_os_string(),
+        }
+    }
+}
+
+/// Wire format for [`OutputFile::flavor`], mirroring `FileFlavor`'s
+/// variants so external tools don't need to depend on Cargo internals.
+#[derive(serde::Deserialize, serde::Serialize)]
+enum ExtFileFlavor {
+    Normal,
+    Auxiliary,
+    Linkable { rmeta: bool },
+    DebugInfo,
+    Rmeta,
+}
+
+impl ExtFileFlavor {
+    fn mk_flavor(&self) -> FileFlavor {
+        match self {
+            ExtFileFlavor::Normal => FileFlavor::Normal,
+            ExtFileFlavor::Auxiliary => FileFlavor::Auxiliary,
+            ExtFileFlavor::Linkable { rmeta } => FileFlavor::Linkable { rmeta: *rmeta },
+            ExtFileFlavor::DebugInfo => FileFlavor::DebugInfo,
+            ExtFileFlavor::Rmeta => FileFlavor::Rmeta,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize)]
+struct ExtOutputFile {
+    path: OsString,
+    hardlink: Option<OsString>,
+    export_path: Option<OsString>,
+    flavor: ExtFileFlavor,
+}
+
+impl ExtOutputFile {
+    fn mk_output_file(&self) -> OutputFile {
+        OutputFile {
+            path: PathBuf::from(&self.path),
+            hardlink: self.hardlink.as_ref().map(PathBuf::from),
+            export_path: self.export_path.as_ref().map(PathBuf::from),
+            flavor: self.flavor.mk_flavor(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize)]
+enum OutputsResult {
+    Success { outputs: Vec<ExtOutputFile> },
+    Failure { message: String },
+}
+
+/// What [`ExternalBuildMgr::compiler`] produced: either something to
+/// actually spawn, or the `--build-plan` entries that stand in for it.
+pub enum CompileAction {
+    /// Spawn this to run the build for real.
+    Spawn(ProcessBuilder),
+    /// `--build-plan` was requested: these are the invocations to
+    /// merge into the overall plan instead of running anything.
+    Plan(Vec<Invocation>),
+}
+
+/// One process invocation in the `--build-plan` JSON document, in the
+/// same shape Cargo already emits for rustc units.
+#[derive(serde::Serialize)]
+pub struct Invocation {
+    pub program: PathBuf,
+    pub args: Vec<OsString>,
+    pub env: HashMap<String, String>,
+    pub cwd: Option<PathBuf>,
+    pub links: HashMap<PathBuf, PathBuf>,
+    pub outputs: Vec<PathBuf>,
+    pub deps: Vec<usize>,
+}
+
+/// Wire format for a single invocation reported by the `build-plan`
+/// subcommand. `deps` is intentionally absent: only alen knows the
+/// overall unit graph's numbering, so it fills `deps` in itself from
+/// `target_deps` after matching invocations up by `target_name`.
+#[derive(serde::Deserialize)]
+struct ExtInvocation {
+    target_name: String,
+    program: OsString,
+    args: Vec<OsString>,
+    env: HashMap<String, String>,
+    cwd: Option<OsString>,
+    links: HashMap<OsString, OsString>,
+    outputs: Vec<OsString>,
+}
+
+impl ExtInvocation {
+    fn mk_invocation(&self, deps: Vec<usize>) -> Invocation {
+        Invocation {
+            program: PathBuf::from(&self.program),
+            args: self.args.clone(),
+            env: self.env.clone(),
+            cwd: self.cwd.as_ref().map(PathBuf::from),
+            links: self
+                .links
+                .iter()
+                .map(|(src, dst)| (PathBuf::from(src), PathBuf::from(dst)))
+                .collect(),
+            outputs: self.outputs.iter().map(PathBuf::from).collect(),
+            deps,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+enum BuildPlanResult {
+    Success { invocations: Vec<ExtInvocation> },
+    Failure { message: String },
+}
+
+/// A span within `DiagnosticLine`, identifying where a diagnostic
+/// applies in a source file.
+#[derive(serde::Deserialize, serde::Serialize)]
+struct DiagnosticSpan {
+    file: PathBuf,
+    line_start: usize,
+    col_start: usize,
+    line_end: usize,
+    col_end: usize,
+}
+
+/// One line of the newline-delimited JSON diagnostic protocol the
+/// external `build` subcommand may emit on stdout.
+#[derive(serde::Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum BuildLine {
+    Diagnostic {
+        level: String,
+        message: String,
+        spans: Vec<DiagnosticSpan>,
+    },
+    Artifact {
+        target: String,
+        filenames: Vec<PathBuf>,
+    },
+    Progress {
+        pct: f64,
+        message: String,
+    },
+}
+
+/// Sink for per-unit timing events, implemented by Cargo's `--timings`
+/// collector. Kept as a trait so this module doesn't need to depend on
+/// the concrete timings collector type.
+pub trait TimingsSink {
+    /// Record that `build_id`'s `target` started running at `start`.
+    fn record_start(&mut self, build_id: &str, target: &str, start: Instant);
+    /// Record that `build_id`'s `target` finished running at `finish`.
+    fn record_finish(&mut self, build_id: &str, target: &str, finish: Instant);
+    /// Record a progress annotation the external tool reported
+    /// mid-build, so a slow external toolchain doesn't appear as one
+    /// opaque bar in the Gantt-style report.
+    fn record_progress(&mut self, build_id: &str, target: &str, pct: f64, message: &str);
+}
+
+/// Mirrors Cargo's `compiler-message` machine-readable message.
+#[derive(serde::Serialize)]
+struct CompilerMessage {
+    reason: &'static str,
+    level: String,
+    message: String,
+    spans: Vec<DiagnosticSpan>,
+}
+
+/// Mirrors Cargo's `compiler-artifact` machine-readable message.
+#[derive(serde::Serialize)]
+struct CompilerArtifact {
+    reason: &'static str,
+    target: String,
+    filenames: Vec<PathBuf>,
+}
+
+/// Path within a unit's fingerprint directory where alen records the
+/// dep-info state the external tool reported on its last successful
+/// `build`, so a later build can decide freshness without re-running
+/// the tool.
+fn dep_info_record_path(fingerprint_dir: &Path) -> PathBuf {
+    fingerprint_dir.join("external-dep-info.json")
+}
+
+/// One input file alen is tracking for freshness, with the mtime it
+/// had the last time the external tool reported reading it.
+#[derive(serde::Deserialize, serde::Serialize)]
+struct RecordedInput {
+    path: PathBuf,
+    mtime: SystemTime,
+}
+
+/// Recorded dep-info state for a unit: the toolchain hash in effect
+/// when it was recorded, plus every input the external tool read.
+#[derive(serde::Deserialize, serde::Serialize)]
+struct RecordedDepInfo {
+    toolchain_hash: u64,
+    inputs: Vec<RecordedInput>,
+}
+
+/// Read the dep-info file an external tool writes after `build` runs,
+/// analogous to rustc's `.d` files: newline-separated paths, resolved
+/// relative to `package_root`, one per input the build read. Returns
+/// `None` if the tool didn't write the file at all, which callers must
+/// treat as "always dirty" rather than "no inputs".
+fn read_reported_dep_info(
+    dep_info_file: &Path,
+    package_root: &Path,
+) -> CargoResult<Option<Vec<PathBuf>>> {
+    match fs::read_to_string(dep_info_file) {
+        Ok(contents) => Ok(Some(
+            contents
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(|line| package_root.join(line))
+                .collect(),
+        )),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Destination for the lines [`emit_build_line`] renders from a
+/// `build` subcommand's stdout, kept as a trait (like [`TimingsSink`])
+/// so protocol parsing stays decoupled from any concrete output and is
+/// testable without capturing process stdio.
+pub trait BuildMessageSink {
+    /// A human-readable diagnostic or artifact notice.
+    fn message(&mut self, line: &str);
+    /// A `compiler-message`/`compiler-artifact` JSON protocol line.
+    fn json_message(&mut self, line: &str);
+}
+
+/// The production [`BuildMessageSink`]: human text to stderr, JSON
+/// protocol lines to stdout, matching how `rustc`'s own JSON message
+/// protocol is surfaced today.
+pub struct StdioBuildMessageSink;
+
+impl BuildMessageSink for StdioBuildMessageSink {
+    fn message(&mut self, line: &str) {
+        eprintln!("{}", line);
+    }
+
+    fn json_message(&mut self, line: &str) {
+        println!("{}", line);
+    }
+}
+
+/// Render one parsed `build` line, either as a Cargo `compiler-message`/
+/// `compiler-artifact` JSON line, or as plain text when human-readable
+/// output was requested, and hand it to `messages` rather than
+/// printing directly.
+fn emit_build_line(line: BuildLine, human: bool, messages: &mut dyn BuildMessageSink) {
+    match line {
+        BuildLine::Diagnostic {
+            level,
+            message,
+            spans,
+        } => {
+            if human {
+                if spans.is_empty() {
+                    messages.message(&format!("{}: {}", level, message));
+                }
+                for span in &spans {
+                    messages.message(&format!(
+                        "{}: {}\n  --> {}:{}:{}",
+                        level,
+                        message,
+                        span.file.display(),
+                        span.line_start,
+                        span.col_start
+                    ));
+                }
+            } else {
+                let msg = CompilerMessage {
+                    reason: "compiler-message",
+                    level,
+                    message,
+                    spans,
+                };
+                if let Ok(json) = serde_json::to_string(&msg) {
+                    messages.json_message(&json);
+                }
+            }
+        }
+        BuildLine::Artifact { target, filenames } => {
+            if human {
+                messages.message(&format!(
+                    "artifact: {} ({})",
+                    target,
+                    filenames
+                        .iter()
+                        .map(|f| f.display().to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            } else {
+                let artifact = CompilerArtifact {
+                    reason: "compiler-artifact",
+                    target,
+                    filenames,
+                };
+                if let Ok(json) = serde_json::to_string(&artifact) {
+                    messages.json_message(&json);
+                }
+            }
+        }
+        // Progress lines are routed to `TimingsSink` by the caller
+        // before `emit_build_line` is reached; see `ExternalBuildMgr::run_build`.
+        BuildLine::Progress { .. } => {}
+    }
+}
+
 impl ExternalBuildMgr {
     /// Create a new build system
     pub fn new<'a>(search_paths: impl Iterator<Item = &'a PathBuf>) -> Self {
@@ -195,9 +746,7 @@ impl ExternalBuildMgr {
         let ecode = child
             .wait()
             .map_err(|_| anyhow::format_err!("{} failed to terminate", runner.path.display()))?;
-        if !ecode.success() {
-            bail!("{} exited with {}", runner.path.display(), ecode);
-        }
+        check_exit_status(&runner.path, ecode)?;
 
         match json_result {
             TargetResult::Success {
@@ -219,19 +768,503 @@ impl ExternalBuildMgr {
 
     /// This returns the hash of the toolchain for the given build system.
     pub fn toolchain_hash(&self, build_id: &str) -> CargoResult<u64> {
-        Ok(self.build_system(build_id)?.hash())
+        self.build_system(build_id)?.hash()
+    }
+
+    /// Return outputs for unit, as reported by the `outputs` subcommand.
+    pub fn outputs(
+        &self,
+        build_id: &str,
+        unit: &Unit,
+        out_dir: &Path,
+    ) -> CargoResult<Vec<OutputFile>> {
+        let runner = self.build_system(build_id)?;
+
+        let mut command = Command::new(runner.path.as_os_str());
+        command.arg("outputs");
+        command.env_clear();
+        command.stdin(Stdio::piped());
+        command.stdout(Stdio::piped());
+        command.stderr(Stdio::null());
+        let mut child = command
+            .spawn()
+            .map_err(|_| anyhow::format_err!("Could not launch {}", runner.path.display()))?;
+
+        let stdin = child.stdin.take().unwrap();
+        let req = UnitRequest::new(unit, out_dir);
+        serde_json::to_writer(stdin, &req)?;
+
+        let mut stdout = child.stdout.take().unwrap();
+        let mut buffer = String::new();
+        stdout.read_to_string(&mut buffer)?;
+        let json_result: OutputsResult = serde_json::from_str(&buffer)
+            .with_context(|| format!("Invalid outputs result from `{}`", runner.path.display()))?;
+        let ecode = child
+            .wait()
+            .map_err(|_| anyhow::format_err!("{} failed to terminate", runner.path.display()))?;
+        check_exit_status(&runner.path, ecode)?;
+
+        match json_result {
+            OutputsResult::Success { outputs } => {
+                Ok(outputs.iter().map(ExtOutputFile::mk_output_file).collect())
+            }
+            OutputsResult::Failure { message } => Err(anyhow::format_err!(message)),
+        }
     }
 
-    /// Return outputs for unit.
-    pub fn outputs(&self, _build_id: &str, _unit: &Unit) -> CargoResult<Vec<OutputFile>> {
-        Ok(vec![]) // TODO: FIXME
+    /// Return the `--build-plan` invocations for the given build system.
+    ///
+    /// `target_deps` maps each target name the tool reports back to the
+    /// `deps` indices alen has already assigned those units in the
+    /// overall build-plan graph; the tool itself never sees, and never
+    /// produces, graph indices.
+    pub fn build_plan(
+        &self,
+        build_id: &str,
+        target_deps: &HashMap<String, Vec<usize>>,
+    ) -> CargoResult<Vec<Invocation>> {
+        let runner = self.build_system(build_id)?;
+
+        let mut command = Command::new(runner.path.as_os_str());
+        command.arg("build-plan");
+        command.env_clear();
+        command.stdin(Stdio::null());
+        command.stdout(Stdio::piped());
+        command.stderr(Stdio::null());
+        let mut child = command
+            .spawn()
+            .map_err(|_| anyhow::format_err!("Could not launch {}", runner.path.display()))?;
+
+        let mut stdout = child.stdout.take().unwrap();
+        let mut buffer = String::new();
+        stdout.read_to_string(&mut buffer)?;
+        let json_result: BuildPlanResult = serde_json::from_str(&buffer).with_context(|| {
+            format!("Invalid build-plan result from `{}`", runner.path.display())
+        })?;
+        let ecode = child
+            .wait()
+            .map_err(|_| anyhow::format_err!("{} failed to terminate", runner.path.display()))?;
+        check_exit_status(&runner.path, ecode)?;
+
+        match json_result {
+            BuildPlanResult::Success { invocations } => Ok(invocations
+                .iter()
+                .map(|inv| {
+                    let deps = target_deps
+                        .get(&inv.target_name)
+                        .cloned()
+                        .unwrap_or_default();
+                    inv.mk_invocation(deps)
+                })
+                .collect()),
+            BuildPlanResult::Failure { message } => Err(anyhow::format_err!(message)),
+        }
     }
 
-    /// Run the compiler for the given build system
-    pub fn compiler(&self, build_id: &str) -> CargoResult<ProcessBuilder> {
+    /// Either spawn the compiler for the given build system, or emit
+    /// its `--build-plan` entry, depending on `build_plan`.
+    ///
+    /// Unlike a bare flag callers could ignore, this forces the two
+    /// cases apart at the type level: when `build_plan` is `Some`, no
+    /// `ProcessBuilder` is ever constructed, so it's not possible to
+    /// accidentally spawn a real build while `--build-plan` is active.
+    pub fn compiler(
+        &self,
+        build_id: &str,
+        dep_info_file: &Path,
+        build_plan: Option<&HashMap<String, Vec<usize>>>,
+    ) -> CargoResult<CompileAction> {
+        if let Some(target_deps) = build_plan {
+            return Ok(CompileAction::Plan(self.build_plan(build_id, target_deps)?));
+        }
+
         let r = self.build_system(build_id)?;
         let mut cmd = r.command();
-        cmd.arg("build");
-        Ok(cmd)
+        cmd.arg("build").arg("--dep-info").arg(dep_info_file);
+        Ok(CompileAction::Spawn(cmd))
+    }
+
+    /// Run the `build` command for `build_id`, translating the
+    /// newline-delimited JSON diagnostics and artifacts it writes to
+    /// stdout into Cargo's own `compiler-message`/`compiler-artifact`
+    /// schema. `human` renders plain text instead, for when
+    /// `--message-format=human` is in effect. `dep_info_file` is where
+    /// the tool is asked to write the dep-info consumed by
+    /// [`ExternalBuildMgr::record_dep_info`].
+    ///
+    /// `target` and `timings` let external units show up in the
+    /// `--timings` report the same way rustc units already do: the
+    /// spawn and exit instants are recorded as the unit's start/finish,
+    /// and any `progress` lines the tool emits are forwarded as
+    /// timing annotations in between. Rendered diagnostic/artifact
+    /// lines go to `messages` rather than directly to stdout/stderr,
+    /// so callers (and tests) control where build output lands.
+    pub fn run_build(
+        &self,
+        build_id: &str,
+        target: &str,
+        dep_info_file: &Path,
+        human: bool,
+        timings: &mut dyn TimingsSink,
+        messages: &mut dyn BuildMessageSink,
+    ) -> CargoResult<()> {
+        let runner = self.build_system(build_id)?;
+
+        let mut command = runner.command_std();
+        command.arg("build").arg("--dep-info").arg(dep_info_file);
+        command.stdin(Stdio::null());
+        command.stdout(Stdio::piped());
+        command.stderr(Stdio::inherit());
+        let mut child = command
+            .spawn()
+            .map_err(|_| anyhow::format_err!("Could not launch {}", runner.path.display()))?;
+        timings.record_start(build_id, target, Instant::now());
+
+        // Read and parse stdout in a closure so that any I/O or parse
+        // error still falls through to the `wait()` below: `Child` is
+        // not killed or reaped on drop, and propagating early via `?`
+        // here would leave a zombie/orphaned process behind every time
+        // the tool misbehaves.
+        let stdout = child.stdout.take().unwrap();
+        let read_result: CargoResult<()> = (|| {
+            for line in BufReader::new(stdout).lines() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let parsed: BuildLine = serde_json::from_str(&line).with_context(|| {
+                    format!(
+                        "Invalid build diagnostic from `{}`: {}",
+                        runner.path.display(),
+                        line
+                    )
+                })?;
+                if let BuildLine::Progress { pct, message } = &parsed {
+                    timings.record_progress(build_id, target, *pct, message);
+                } else {
+                    emit_build_line(parsed, human, messages);
+                }
+            }
+            Ok(())
+        })();
+
+        if read_result.is_err() {
+            let _ = child.kill();
+        }
+        let ecode = child
+            .wait()
+            .map_err(|_| anyhow::format_err!("{} failed to terminate", runner.path.display()))?;
+        timings.record_finish(build_id, target, Instant::now());
+        read_result?;
+        check_exit_status(&runner.path, ecode)
+    }
+
+    /// Check whether `build_id`'s last recorded dep-info state in
+    /// `fingerprint_dir` is still fresh: every recorded input is
+    /// unchanged on disk and the toolchain hash still matches. No
+    /// recorded state at all (first build, or a previous build whose
+    /// tool never reported dep-info) is always stale.
+    pub fn is_fresh(&self, build_id: &str, fingerprint_dir: &Path) -> CargoResult<bool> {
+        let record = match fs::read_to_string(dep_info_record_path(fingerprint_dir)) {
+            Ok(contents) => contents,
+            Err(_) => return Ok(false),
+        };
+        let record: RecordedDepInfo = match serde_json::from_str(&record) {
+            Ok(record) => record,
+            Err(_) => return Ok(false),
+        };
+
+        if record.toolchain_hash != self.toolchain_hash(build_id)? {
+            return Ok(false);
+        }
+
+        for input in &record.inputs {
+            let mtime = match fs::metadata(&input.path).and_then(|m| m.modified()) {
+                Ok(mtime) => mtime,
+                Err(_) => return Ok(false),
+            };
+            if mtime != input.mtime {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Record the dep-info state reported in `dep_info_file` (written
+    /// by a successful `build`) into `fingerprint_dir`, so the next
+    /// build can check [`ExternalBuildMgr::is_fresh`] instead of
+    /// always re-running the external tool. If the tool didn't write
+    /// `dep_info_file`, any previous record is dropped so the unit is
+    /// always considered dirty until a build reports dep-info again.
+    pub fn record_dep_info(
+        &self,
+        build_id: &str,
+        package_root: &Path,
+        dep_info_file: &Path,
+        fingerprint_dir: &Path,
+    ) -> CargoResult<()> {
+        let dest = dep_info_record_path(fingerprint_dir);
+        let inputs = match read_reported_dep_info(dep_info_file, package_root)? {
+            Some(inputs) => inputs,
+            None => {
+                let _ = fs::remove_file(&dest);
+                return Ok(());
+            }
+        };
+
+        let inputs = inputs
+            .into_iter()
+            .map(|path| {
+                let mtime = fs::metadata(&path)
+                    .with_context(|| format!("failed to stat {}", path.display()))?
+                    .modified()?;
+                Ok(RecordedInput { path, mtime })
+            })
+            .collect::<CargoResult<Vec<_>>>()?;
+
+        let record = RecordedDepInfo {
+            toolchain_hash: self.toolchain_hash(build_id)?,
+            inputs,
+        };
+        fs::create_dir_all(fingerprint_dir)?;
+        fs::write(dest, serde_json::to_string(&record)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn siphash13_matches_known_test_vectors() {
+        // Canonical SipHash test key: k0/k1 are the little-endian
+        // reads of bytes 0x00..=0x0f, as used by the reference
+        // implementation's test vectors for every SipHash variant.
+        const K0: u64 = 0x0706050403020100;
+        const K1: u64 = 0x0f0e0d0c0b0a0908;
+
+        assert_eq!(
+            siphash13_with_key(K0, K1, &[]),
+            0xabac0158050fc4dc,
+            "SipHash-1-3 of the empty input under the canonical test key"
+        );
+        assert_eq!(
+            siphash13_with_key(K0, K1, &[0x00]),
+            0xc9f49bf37d57ca93,
+            "SipHash-1-3 of a single input byte under the canonical test key"
+        );
+    }
+
+    #[test]
+    fn siphash13_is_deterministic() {
+        let a = siphash13(b"cargobuild-example fingerprint");
+        let b = siphash13(b"cargobuild-example fingerprint");
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn siphash13_distinguishes_inputs() {
+        assert_ne!(siphash13(b"1.0.0"), siphash13(b"1.0.1"));
+        assert_ne!(siphash13(b""), siphash13(b"\0"));
+    }
+
+    #[test]
+    fn siphash13_handles_block_boundary_lengths() {
+        // Exercise the chunked-reader path around the 8-byte block
+        // size: one byte short of, exactly at, and one byte past a
+        // full block, plus a length spanning several blocks.
+        for len in [0usize, 1, 7, 8, 9, 15, 16, 17, 63, 64, 65] {
+            let data: Vec<u8> = (0..len as u32).map(|b| (b % 251) as u8).collect();
+            // Must not panic, and must be stable across repeated calls.
+            let first = siphash13(&data);
+            let second = siphash13(&data);
+            assert_eq!(first, second, "siphash13 not stable for len={}", len);
+        }
+    }
+
+    /// A unique scratch directory under the OS temp dir, for tests
+    /// that need real files on disk (mtimes aren't observable on
+    /// in-memory fixtures). Cleaned up by the caller.
+    fn scratch_dir(tag: &str) -> PathBuf {
+        static COUNTER: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+        let n = COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let dir = std::env::temp_dir().join(format!(
+            "alen-external-test-{}-{}-{}",
+            std::process::id(),
+            tag,
+            n
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Build an `ExternalBuildMgr` with a single build system whose
+    /// toolchain hash is fixed at `hash`, bypassing the real
+    /// `fingerprint` subprocess protocol so these tests don't need a
+    /// `cargobuild-*` executable on disk.
+    fn mgr_with_fixed_hash(build_id: &str, hash: u64) -> ExternalBuildMgr {
+        let mut build_systems = HashMap::new();
+        build_systems.insert(
+            build_id.to_string(),
+            BuildSystem {
+                path: PathBuf::from("/nonexistent/cargobuild-test"),
+                hash: Mutex::new(Some(hash)),
+            },
+        );
+        ExternalBuildMgr { build_systems }
+    }
+
+    #[test]
+    fn dep_info_round_trip_freshness() {
+        let dir = scratch_dir("dep-info");
+        let package_root = dir.join("pkg");
+        let fingerprint_dir = dir.join("fingerprint");
+        fs::create_dir_all(&package_root).unwrap();
+        fs::create_dir_all(&fingerprint_dir).unwrap();
+
+        let input_path = package_root.join("input.txt");
+        fs::write(&input_path, b"v1").unwrap();
+        let dep_info_file = dir.join("dep-info.txt");
+        fs::write(&dep_info_file, b"input.txt\n").unwrap();
+
+        let mgr = mgr_with_fixed_hash("test", 7);
+
+        // No record yet: always dirty.
+        assert!(!mgr.is_fresh("test", &fingerprint_dir).unwrap());
+
+        mgr.record_dep_info("test", &package_root, &dep_info_file, &fingerprint_dir)
+            .unwrap();
+        assert!(
+            mgr.is_fresh("test", &fingerprint_dir).unwrap(),
+            "fresh immediately after recording with no changes"
+        );
+
+        // Bump the input's mtime: now stale.
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        fs::write(&input_path, b"v2").unwrap();
+        assert!(
+            !mgr.is_fresh("test", &fingerprint_dir).unwrap(),
+            "stale after a recorded input's mtime changes"
+        );
+
+        // Re-record against the new content, then change just the
+        // toolchain hash: still stale.
+        mgr.record_dep_info("test", &package_root, &dep_info_file, &fingerprint_dir)
+            .unwrap();
+        assert!(mgr.is_fresh("test", &fingerprint_dir).unwrap());
+        let mgr_new_toolchain = mgr_with_fixed_hash("test", 8);
+        assert!(
+            !mgr_new_toolchain
+                .is_fresh("test", &fingerprint_dir)
+                .unwrap(),
+            "stale after the toolchain hash changes"
+        );
+
+        // The external tool failing to write dep-info on the next
+        // build drops the record, so the unit is dirty again.
+        fs::remove_file(&dep_info_file).unwrap();
+        mgr.record_dep_info("test", &package_root, &dep_info_file, &fingerprint_dir)
+            .unwrap();
+        assert!(
+            !mgr.is_fresh("test", &fingerprint_dir).unwrap(),
+            "stale when the external tool reports no dep-info file"
+        );
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    /// A [`BuildMessageSink`] that records lines instead of printing
+    /// them, so [`emit_build_line`]'s human-format branch is testable.
+    #[derive(Default)]
+    struct RecordingSink {
+        messages: Vec<String>,
+        json_messages: Vec<String>,
+    }
+
+    impl BuildMessageSink for RecordingSink {
+        fn message(&mut self, line: &str) {
+            self.messages.push(line.to_string());
+        }
+
+        fn json_message(&mut self, line: &str) {
+            self.json_messages.push(line.to_string());
+        }
+    }
+
+    #[test]
+    fn emit_build_line_human_renders_diagnostics_and_artifacts() {
+        let mut sink = RecordingSink::default();
+
+        emit_build_line(
+            BuildLine::Diagnostic {
+                level: "warning".to_string(),
+                message: "unused variable".to_string(),
+                spans: vec![],
+            },
+            true,
+            &mut sink,
+        );
+        emit_build_line(
+            BuildLine::Diagnostic {
+                level: "error".to_string(),
+                message: "type mismatch".to_string(),
+                spans: vec![DiagnosticSpan {
+                    file: PathBuf::from("src/lib.foo"),
+                    line_start: 3,
+                    col_start: 5,
+                    line_end: 3,
+                    col_end: 9,
+                }],
+            },
+            true,
+            &mut sink,
+        );
+        emit_build_line(
+            BuildLine::Artifact {
+                target: "mylib".to_string(),
+                filenames: vec![PathBuf::from("target/debug/mylib.out")],
+            },
+            true,
+            &mut sink,
+        );
+
+        assert_eq!(
+            sink.messages,
+            vec![
+                "warning: unused variable".to_string(),
+                "error: type mismatch\n  --> src/lib.foo:3:5".to_string(),
+                "artifact: mylib (target/debug/mylib.out)".to_string(),
+            ]
+        );
+        assert!(sink.json_messages.is_empty());
+    }
+
+    #[test]
+    fn emit_build_line_json_renders_protocol_lines() {
+        let mut sink = RecordingSink::default();
+
+        emit_build_line(
+            BuildLine::Diagnostic {
+                level: "warning".to_string(),
+                message: "unused variable".to_string(),
+                spans: vec![],
+            },
+            false,
+            &mut sink,
+        );
+        emit_build_line(
+            BuildLine::Artifact {
+                target: "mylib".to_string(),
+                filenames: vec![PathBuf::from("target/debug/mylib.out")],
+            },
+            false,
+            &mut sink,
+        );
+
+        assert!(sink.messages.is_empty());
+        assert_eq!(sink.json_messages.len(), 2);
+        assert!(sink.json_messages[0].contains("\"reason\":\"compiler-message\""));
+        assert!(sink.json_messages[1].contains("\"reason\":\"compiler-artifact\""));
     }
 }